@@ -1,10 +1,12 @@
 use super::CrateVersion;
 use serde_json;
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use git2::{
-    build::RepoBuilder, Delta, DiffFormat, Error as GitError, ErrorClass, Object, ObjectType, Oid,
-    Reference, Repository, Tree,
+    build::RepoBuilder, Delta, DiffFormat, Error as GitError, ErrorClass, ErrorCode, Object,
+    ObjectType, Oid, Reference, Repository, Tree,
 };
 use std::str;
 
@@ -12,19 +14,144 @@ static INDEX_GIT_URL: &str = "https://github.com/rust-lang/crates.io-index";
 static LAST_SEEN_REFNAME: &str = "refs/heads/crates-index-diff_last-seen";
 static EMPTY_TREE_HASH: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
 static LINE_ADDED_INDICATOR: char = '+';
+static LINE_REMOVED_INDICATOR: char = '-';
+/// How many diffed lines pass between checks of `should_interrupt`.
+static INTERRUPT_CHECK_INTERVAL: usize = 256;
+
+/// A sink for progress information emitted while fetching and diffing the index, with support
+/// for naming independently tracked sub-tasks.
+///
+/// `Discard` is the implementation used throughout this crate's convenience methods, which don't
+/// take a `Progress` of their own.
+pub trait Progress {
+    /// Begin a new sub-task named `name`, returning a handle to report progress on it.
+    fn add_child(&mut self, name: &str) -> Box<dyn Progress>;
+    /// Advance the current task's counter by `steps`.
+    fn inc_by(&mut self, steps: usize);
+    /// Set the current task's counter to the absolute `value`.
+    fn set(&mut self, value: usize);
+}
+
+/// A `Progress` implementation that discards everything it is given.
+pub struct Discard;
+
+impl Progress for Discard {
+    fn add_child(&mut self, _name: &str) -> Box<dyn Progress> {
+        Box::new(Discard)
+    }
+    fn inc_by(&mut self, _steps: usize) {}
+    fn set(&mut self, _value: usize) {}
+}
+
+/// Build the error returned when an operation aborts because `should_interrupt` was observed set,
+/// distinguishable from other failures via `was_interrupted()`.
+fn interrupted_error() -> GitError {
+    GitError::new(ErrorCode::User, ErrorClass::None, "operation was interrupted")
+}
+
+/// Return whether `err` was produced because a `should_interrupt` flag was set, as opposed to any
+/// other kind of failure.
+pub fn was_interrupted(err: &GitError) -> bool {
+    err.code() == ErrorCode::User
+}
+
+/// A change observed for a particular `(name, version)` entry of the index between two states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A new version of a crate was published.
+    Added(CrateVersion),
+    /// A previously published version was marked as yanked.
+    Yanked(CrateVersion),
+    /// A previously yanked version was un-yanked.
+    Unyanked(CrateVersion),
+    /// A version's entry was removed from the index entirely.
+    Deleted {
+        /// The name of the crate that lost a version.
+        name: String,
+        /// The version that was removed.
+        version: String,
+    },
+}
+
+impl Change {
+    /// Return the newly added `CrateVersion`, if this change is an `Added`.
+    pub fn added(&self) -> Option<&CrateVersion> {
+        match self {
+            Change::Added(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Flatten `changes` down to the `CrateVersion`s of its `Added` entries, discarding yanks,
+    /// unyanks and deletions. This is a drop-in replacement for callers written against the
+    /// `Vec<CrateVersion>` this crate's methods used to return before they started reporting
+    /// every kind of change.
+    pub fn into_added(changes: Vec<Change>) -> Vec<CrateVersion> {
+        changes.into_iter().filter_map(|c| c.added().cloned()).collect()
+    }
+}
 
 /// A wrapper for a repository of the crates.io index.
 pub struct Index {
     /// The name and path of the reference used to keep track of the last seen state of the
     /// crates.io repository. The default value is `refs/heads/crates-index-diff_last-seen`.
     pub seen_ref_name: &'static str,
+    /// The name of the remote to fetch from. If unset, it is determined from the checked-out
+    /// branch's configured upstream, the repository's `clone.defaultRemoteName`, or `origin` as
+    /// the final fallback. Given as-is, even if it happens to look like a URL.
+    pub remote_name: Option<String>,
+    /// The name of the branch to track on the remote, e.g. `master`. If unset, it is determined
+    /// from the checked-out branch, or `master` as the final fallback.
+    pub branch_name: Option<String>,
     /// The crates.io repository.
     repo: Repository,
 }
 
 /// Options for use in `Index::from_path_or_cloned_with_options`
-pub struct CloneOptions {
+pub struct CloneOptions<'cb> {
     repository_url: String,
+    depth: Option<u32>,
+    fetch_options: Option<git2::FetchOptions<'cb>>,
+}
+
+impl<'cb> CloneOptions<'cb> {
+    /// Create options that clone the official crates.io index with complete history.
+    pub fn new() -> Self {
+        CloneOptions {
+            repository_url: INDEX_GIT_URL.into(),
+            depth: None,
+            fetch_options: None,
+        }
+    }
+
+    /// Clone from `url` instead of the official crates.io index.
+    pub fn with_repository_url(mut self, url: impl Into<String>) -> Self {
+        self.repository_url = url.into();
+        self
+    }
+
+    /// Limit the initial clone to the most recent `depth` commits instead of the complete
+    /// history. `last_seen_reference()` is seeded to the newest commit of the shallow clone (the
+    /// one its `HEAD` points to), so subsequent calls still see a correct diff starting from that
+    /// point.
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Set the `FetchOptions` used for the initial clone, e.g. to supply a credentials callback,
+    /// a proxy configuration, or a custom certificate check via its `RemoteCallbacks`. If
+    /// `depth()` is also set, it overrides any depth already configured on `fetch_options`.
+    pub fn fetch_options(mut self, fetch_options: git2::FetchOptions<'cb>) -> Self {
+        self.fetch_options = Some(fetch_options);
+        self
+    }
+}
+
+impl<'cb> Default for CloneOptions<'cb> {
+    fn default() -> Self {
+        CloneOptions::new()
+    }
 }
 
 impl Index {
@@ -41,20 +168,33 @@ impl Index {
     /// Return a new `Index` instance from the given `path`, which should contain a bare or non-bare
     /// clone of the `crates.io` index.
     /// If the directory does not contain the repository or does not exist, it will be cloned from
-    /// the official location automatically (with complete history).
+    /// the official location automatically (with complete history, unless `options.depth` is set).
     ///
     /// An error will occour if the repository exists and the remote URL does not match the given repository URL.
-    pub fn from_path_or_cloned_with_options(
+    pub fn from_path_or_cloned_with_options<'cb>(
         path: impl AsRef<Path>,
-        options: CloneOptions,
+        options: CloneOptions<'cb>,
     ) -> Result<Index, GitError> {
+        let CloneOptions {
+            repository_url,
+            depth,
+            fetch_options,
+        } = options;
+
         let mut repo_did_exist = true;
         let repo = Repository::open(path.as_ref()).or_else(|err| {
             if err.class() == ErrorClass::Repository {
                 repo_did_exist = false;
-                RepoBuilder::new()
-                    .bare(true)
-                    .clone(&options.repository_url, path.as_ref())
+                let mut builder = RepoBuilder::new();
+                builder.bare(true);
+                if depth.is_some() || fetch_options.is_some() {
+                    let mut fetch_options = fetch_options.unwrap_or_else(git2::FetchOptions::new);
+                    if let Some(depth) = depth {
+                        fetch_options.depth(depth as i32);
+                    }
+                    builder.fetch_options(fetch_options);
+                }
+                builder.clone(&repository_url, path.as_ref())
             } else {
                 Err(err)
             }
@@ -65,17 +205,30 @@ impl Index {
             let actual_remote_url = remote
                 .url()
                 .ok_or_else(|| GitError::from_str("did not obtain URL of remote named 'origin'"))?;
-            if actual_remote_url != options.repository_url {
+            if actual_remote_url != repository_url {
                 return Err(GitError::from_str(&format!(
                     "Actual 'origin' remote url {:#?} did not match desired one at {:#?}",
-                    actual_remote_url, options.repository_url
+                    actual_remote_url, repository_url
                 )));
             }
+        } else if depth.is_some() {
+            // Seed the last-seen reference to the shallow clone's newest commit, so the first
+            // `fetch_changes()` reports only genuinely new history instead of replaying the
+            // entire (already-known) shallow snapshot as a wave of `Added` changes.
+            let boundary = repo.head()?.peel_to_commit()?.id();
+            repo.reference(
+                LAST_SEEN_REFNAME,
+                boundary,
+                true,
+                "seeding seen-ref at shallow clone boundary",
+            )?;
         }
 
         Ok(Index {
             repo,
             seen_ref_name: LAST_SEEN_REFNAME,
+            remote_name: None,
+            branch_name: None,
         })
     }
 
@@ -84,75 +237,221 @@ impl Index {
     /// If the directory does not contain the repository or does not exist, it will be cloned from
     /// the official location automatically (with complete history).
     pub fn from_path_or_cloned(path: impl AsRef<Path>) -> Result<Index, GitError> {
-        Index::from_path_or_cloned_with_options(
-            path,
-            CloneOptions {
-                repository_url: INDEX_GIT_URL.into(),
-            },
-        )
+        Index::from_path_or_cloned_with_options(path, CloneOptions::new())
     }
 
-    /// As `peek_changes_with_options`, but without the options.
-    pub fn peek_changes(&self) -> Result<(Vec<CrateVersion>, git2::Oid), GitError> {
-        self.peek_changes_with_options(None)
+    /// As `peek_changes_with_options`, but without the options, reporting to nowhere and never
+    /// interrupting.
+    pub fn peek_changes(&self) -> Result<(Vec<Change>, git2::Oid), GitError> {
+        self.peek_changes_with_options(None, &mut Discard, &AtomicBool::new(false))
     }
 
-    /// Return all `CrateVersion`s that are observed between the last time `fetch_changes(…)` was called
+    /// Return all `Change`s that are observed between the last time `fetch_changes(…)` was called
     /// and the latest state of the `crates.io` index repository, which is obtained by fetching
     /// the remote called `origin`.
     /// The `last_seen_reference()` will not be created or updated.
     /// The second field in the returned tuple is the commit object to which the changes were provided.
     /// If one would set the `last_seen_reference()` to that object, the effect is exactly the same
     /// as if `fetch_changes(…)` had been called.
+    ///
+    /// `progress` receives updates on lines diffed, and on objects fetched when `options` is
+    /// `None`. `should_interrupt` is checked periodically during the diff, and during the fetch
+    /// when `options` is `None`; setting it from another thread aborts the operation and returns
+    /// an error for which `was_interrupted()` is `true`.
     pub fn peek_changes_with_options(
         &self,
         options: Option<&mut git2::FetchOptions<'_>>,
-    ) -> Result<(Vec<CrateVersion>, git2::Oid), GitError> {
-        let from = self
-            .last_seen_reference()
-            .and_then(|r| {
-                r.target().ok_or_else(|| {
-                    GitError::from_str("last-seen reference did not have a valid target")
-                })
-            })
-            .or_else(|_| Oid::from_str(EMPTY_TREE_HASH))?;
-        let to = {
-            self.repo.find_remote("origin").and_then(|mut r| {
-                r.fetch(&["refs/heads/*:refs/remotes/origin/*"], options, None)
-            })?;
-            let latest_fetched_commit_oid =
-                self.repo.refname_to_id("refs/remotes/origin/master")?;
-            latest_fetched_commit_oid
-        };
+        progress: &mut dyn Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<(Vec<Change>, git2::Oid), GitError> {
+        let from = self.last_seen_oid()?;
+        let to = self.fetch_and_resolve_remote_head(options, progress, should_interrupt)?;
 
         Ok((
             self.changes_from_objects(
                 &self.repo.find_object(from, None)?,
                 &self.repo.find_object(to, None)?,
+                progress,
+                should_interrupt,
             )?,
             to,
         ))
     }
 
-    /// As `fetch_changes_with_options`, but without the options.
-    pub fn fetch_changes(&self) -> Result<Vec<CrateVersion>, GitError> {
-        self.fetch_changes_with_options(None)
+    /// As `fetch_changes_with_options`, but without the options, reporting to nowhere and never
+    /// interrupting.
+    pub fn fetch_changes(&self) -> Result<Vec<Change>, GitError> {
+        self.fetch_changes_with_options(None, &mut Discard, &AtomicBool::new(false))
     }
 
-    /// Return all `CrateVersion`s that are observed between the last time this method was called
+    /// Return all `Change`s that are observed between the last time this method was called
     /// and the latest state of the `crates.io` index repository, which is obtained by fetching
     /// the remote called `origin`.
     /// The `last_seen_reference()` will be created or adjusted to point to the latest fetched
     /// state, which causes this method to have a different result each time it is called.
+    ///
+    /// See `peek_changes_with_options` for `progress` and `should_interrupt`.
     pub fn fetch_changes_with_options(
         &self,
         options: Option<&mut git2::FetchOptions<'_>>,
-    ) -> Result<Vec<CrateVersion>, GitError> {
-        let (changes, to) = self.peek_changes_with_options(options)?;
+        progress: &mut dyn Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<Vec<Change>, GitError> {
+        let (changes, to) = self.peek_changes_with_options(options, progress, should_interrupt)?;
         self.set_last_seen_reference(to)?;
         Ok(changes)
     }
 
+    /// As `peek_changes_ordered_with_options`, but without the options, reporting to nowhere and
+    /// never interrupting.
+    pub fn peek_changes_ordered(&self) -> Result<(Vec<Change>, git2::Oid), GitError> {
+        self.peek_changes_ordered_with_options(None, &mut Discard, &AtomicBool::new(false))
+    }
+
+    /// As `peek_changes_with_options`, but emits every `Change` across the range in the order the
+    /// underlying commits were made, rather than collapsing the whole range into a single net
+    /// diff. See `changes_from_objects_ordered`.
+    pub fn peek_changes_ordered_with_options(
+        &self,
+        options: Option<&mut git2::FetchOptions<'_>>,
+        progress: &mut dyn Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<(Vec<Change>, git2::Oid), GitError> {
+        let from = self.last_seen_oid()?;
+        let to = self.fetch_and_resolve_remote_head(options, progress, should_interrupt)?;
+
+        Ok((
+            self.changes_from_objects_ordered(
+                &self.repo.find_object(from, None)?,
+                &self.repo.find_object(to, None)?,
+                progress,
+                should_interrupt,
+            )?,
+            to,
+        ))
+    }
+
+    /// As `fetch_changes_ordered_with_options`, but without the options, reporting to nowhere and
+    /// never interrupting.
+    pub fn fetch_changes_ordered(&self) -> Result<Vec<Change>, GitError> {
+        self.fetch_changes_ordered_with_options(None, &mut Discard, &AtomicBool::new(false))
+    }
+
+    /// As `fetch_changes_with_options`, but emits every `Change` across the range in the order
+    /// the underlying commits were made. See `peek_changes_ordered_with_options`.
+    pub fn fetch_changes_ordered_with_options(
+        &self,
+        options: Option<&mut git2::FetchOptions<'_>>,
+        progress: &mut dyn Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<Vec<Change>, GitError> {
+        let (changes, to) =
+            self.peek_changes_ordered_with_options(options, progress, should_interrupt)?;
+        self.set_last_seen_reference(to)?;
+        Ok(changes)
+    }
+
+    /// Return the `Oid` of the last-seen reference, or the empty tree if it does not yet exist.
+    fn last_seen_oid(&self) -> Result<Oid, GitError> {
+        self.last_seen_reference()
+            .and_then(|r| {
+                r.target().ok_or_else(|| {
+                    GitError::from_str("last-seen reference did not have a valid target")
+                })
+            })
+            .or_else(|_| Oid::from_str(EMPTY_TREE_HASH))
+    }
+
+    /// Return the remote to fetch from: `remote_name` if set, otherwise the checked-out branch's
+    /// configured upstream remote, the repository's `clone.defaultRemoteName`, or `origin` as the
+    /// final fallback. The name is returned as-is, even if it happens to look like a URL.
+    fn resolved_remote_name(&self) -> String {
+        self.remote_name.clone().unwrap_or_else(|| {
+            self.checked_out_branch_name()
+                .and_then(|branch| {
+                    self.repo
+                        .branch_upstream_remote(&format!("refs/heads/{}", branch))
+                        .ok()
+                })
+                .and_then(|buf| buf.as_str().map(str::to_owned))
+                .or_else(|| {
+                    self.repo
+                        .config()
+                        .ok()?
+                        .get_string("clone.defaultRemoteName")
+                        .ok()
+                })
+                .unwrap_or_else(|| "origin".into())
+        })
+    }
+
+    /// Return the branch to track on the remote: `branch_name` if set, otherwise the checked-out
+    /// branch, or `master` as the final fallback.
+    fn resolved_branch_name(&self) -> String {
+        self.branch_name
+            .clone()
+            .or_else(|| self.checked_out_branch_name())
+            .unwrap_or_else(|| "master".into())
+    }
+
+    /// Return the short name of the currently checked-out branch, if `HEAD` points to one.
+    fn checked_out_branch_name(&self) -> Option<String> {
+        self.repo
+            .head()
+            .ok()
+            .and_then(|head| head.shorthand().map(str::to_owned))
+    }
+
+    /// Fetch `remote_name` and return the `Oid` its `branch_name` points to afterwards.
+    ///
+    /// If `options` is `None`, this installs its own transfer-progress callback that reports
+    /// objects received to `progress` and aborts the transfer once `should_interrupt` is set. If
+    /// the caller supplies `options` with its own callbacks, those are used as-is instead; wire
+    /// `should_interrupt` into your own `transfer_progress` callback if you need the transfer
+    /// itself to be cancellable, since `git2` does not support composing callbacks.
+    fn fetch_and_resolve_remote_head(
+        &self,
+        options: Option<&mut git2::FetchOptions<'_>>,
+        progress: &mut dyn Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<Oid, GitError> {
+        let mut fetch_progress = progress.add_child("fetch");
+        let remote_name = self.resolved_remote_name();
+        let refspec = format!("refs/heads/*:refs/remotes/{}/*", remote_name);
+
+        let fetch_result = match options {
+            Some(options) => self
+                .repo
+                .find_remote(&remote_name)
+                .and_then(|mut r| r.fetch(&[refspec.as_str()], Some(options), None)),
+            None => {
+                let mut callbacks = git2::RemoteCallbacks::new();
+                callbacks.transfer_progress(|stats| {
+                    fetch_progress.set(stats.received_objects());
+                    !should_interrupt.load(Ordering::SeqCst)
+                });
+                let mut owned_options = git2::FetchOptions::new();
+                owned_options.remote_callbacks(callbacks);
+                self.repo.find_remote(&remote_name).and_then(|mut r| {
+                    r.fetch(&[refspec.as_str()], Some(&mut owned_options), None)
+                })
+            }
+        };
+
+        fetch_result.map_err(|err| {
+            if should_interrupt.load(Ordering::SeqCst) {
+                interrupted_error()
+            } else {
+                err
+            }
+        })?;
+        self.repo.refname_to_id(&format!(
+            "refs/remotes/{}/{}",
+            remote_name,
+            self.resolved_branch_name()
+        ))
+    }
+
     /// Set the last seen reference to the given Oid. It will be created if it does not yet exists.
     pub fn set_last_seen_reference(&self, to: Oid) -> Result<(), GitError> {
         self.last_seen_reference()
@@ -170,7 +469,7 @@ impl Index {
         Ok(())
     }
 
-    /// Return all `CreateVersion`s observed between `from` and `to`. Both parameter are ref-specs
+    /// Return all `Change`s observed between `from` and `to`. Both parameter are ref-specs
     /// pointing to either a commit or a tree.
     /// Learn more about specifying revisions
     /// in the
@@ -179,20 +478,32 @@ impl Index {
         &self,
         from: impl AsRef<str>,
         to: impl AsRef<str>,
-    ) -> Result<Vec<CrateVersion>, GitError> {
+    ) -> Result<Vec<Change>, GitError> {
         self.changes_from_objects(
             &self.repo.revparse_single(from.as_ref())?,
             &self.repo.revparse_single(to.as_ref())?,
+            &mut Discard,
+            &AtomicBool::new(false),
         )
     }
 
     /// Similar to `changes()`, but requires `from` and `to` objects to be provided. They may point
     /// to either `Commit`s or `Tree`s.
+    ///
+    /// Every publish, yank, unyank and deletion observed between the two states is reported as a
+    /// `Change`. If only the newly added versions are of interest, use
+    /// `Change::added()` to filter the result, as previous versions of this crate did implicitly.
+    ///
+    /// `progress` is told about every line diffed, and `should_interrupt` is checked periodically;
+    /// setting it from another thread aborts the diff, returning an error for which
+    /// `was_interrupted()` is `true`.
     pub fn changes_from_objects(
         &self,
         from: &Object,
         to: &Object,
-    ) -> Result<Vec<CrateVersion>, GitError> {
+        progress: &mut dyn Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<Vec<Change>, GitError> {
         fn into_tree<'a>(repo: &'a Repository, obj: &Object) -> Result<Tree<'a>, GitError> {
             repo.find_tree(match obj.kind() {
                 Some(ObjectType::Commit) => obj
@@ -211,24 +522,317 @@ impl Index {
             Some(&into_tree(&self.repo, to)?),
             None,
         )?;
-        let mut res: Vec<CrateVersion> = Vec::new();
-        diff.print(DiffFormat::Patch, |delta, _, diffline| {
-            if diffline.origin() != LINE_ADDED_INDICATOR {
-                return true;
+        let mut diff_progress = progress.add_child("diff");
+        Self::changes_from_diff(diff, &mut *diff_progress, should_interrupt)
+    }
+
+    /// As `changes_from_objects`, but walks every commit between `from` and `to` individually
+    /// instead of diffing `from` and `to` directly, and returns the `Change`s in the order the
+    /// underlying commits were made.
+    ///
+    /// This is done via a `revwalk` rooted at `to` that stops at `from`, diffing each visited
+    /// commit against its parent (or the empty tree, if it has none). Unlike
+    /// `changes_from_objects`, a crate version that is published and later yanked within the
+    /// range is reported as two separate `Change`s instead of being collapsed into its net
+    /// effect, which matters for consumers building an audit log or an incremental mirror.
+    /// `to` must be, or peel to, a commit. If `from` does not peel to a commit, every commit
+    /// reachable from `to` is walked, down to the root commit.
+    ///
+    /// See `changes_from_objects` for `progress` and `should_interrupt`; the latter is also
+    /// checked once per visited commit, in addition to periodically during each commit's diff.
+    pub fn changes_from_objects_ordered(
+        &self,
+        from: &Object,
+        to: &Object,
+        progress: &mut dyn Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<Vec<Change>, GitError> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+        revwalk.push(to.peel_to_commit()?.id())?;
+        if let Ok(from_commit) = from.peel_to_commit() {
+            revwalk.hide(from_commit.id())?;
+        }
+
+        let mut diff_progress = progress.add_child("diff");
+        let mut res = Vec::new();
+        for oid in revwalk {
+            if should_interrupt.load(Ordering::SeqCst) {
+                return Err(interrupted_error());
+            }
+            let commit = self.repo.find_commit(oid?)?;
+            let parent_tree = if commit.parent_count() == 0 {
+                None
+            } else {
+                Some(commit.parent(0)?.tree()?)
+            };
+            let diff =
+                self.repo
+                    .diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+            res.extend(Self::changes_from_diff(
+                diff,
+                &mut *diff_progress,
+                should_interrupt,
+            )?);
+        }
+        Ok(res)
+    }
+
+    /// Bucket the added/removed `CrateVersion` lines of a diff by `(name, version)` and classify
+    /// each key into the `Change` it represents, reporting each line seen to `progress` and
+    /// aborting with `was_interrupted()` true if `should_interrupt` is set while doing so.
+    fn changes_from_diff(
+        diff: git2::Diff,
+        progress: &mut dyn Progress,
+        should_interrupt: &AtomicBool,
+    ) -> Result<Vec<Change>, GitError> {
+        // Keyed by `(name, version)`, holding the line as it looked before and after the diff, so
+        // that a rewritten `yanked` flag can be told apart from a brand-new or removed entry.
+        let mut removed: BTreeMap<(String, String), CrateVersion> = BTreeMap::new();
+        let mut added: BTreeMap<(String, String), CrateVersion> = BTreeMap::new();
+        let mut lines_seen: usize = 0;
+        let mut interrupted = false;
+
+        let result = diff.print(DiffFormat::Patch, |delta, _, diffline| {
+            lines_seen += 1;
+            progress.inc_by(1);
+            if lines_seen.is_multiple_of(INTERRUPT_CHECK_INTERVAL)
+                && should_interrupt.load(Ordering::SeqCst)
+            {
+                interrupted = true;
+                return false;
             }
 
             if !match delta.status() {
-                Delta::Added | Delta::Modified => true,
+                Delta::Added | Delta::Modified | Delta::Deleted => true,
                 _ => false,
             } {
                 return true;
             }
 
-            if let Ok(c) = serde_json::from_slice(diffline.content()) {
-                res.push(c)
+            let origin = diffline.origin();
+            let bucket = if origin == LINE_ADDED_INDICATOR {
+                &mut added
+            } else if origin == LINE_REMOVED_INDICATOR {
+                &mut removed
+            } else {
+                return true;
+            };
+
+            if let Ok(c) = serde_json::from_slice::<CrateVersion>(diffline.content()) {
+                bucket.insert((c.name.clone(), c.vers.clone()), c);
             }
             true
-        })
-        .map(|_| res)
+        });
+
+        if interrupted {
+            return Err(interrupted_error());
+        }
+        result?;
+
+        let mut res = Vec::new();
+        for (key, new) in added {
+            match removed.remove(&key) {
+                Some(old) => match (old.yanked, new.yanked) {
+                    (false, true) => res.push(Change::Yanked(new)),
+                    (true, false) => res.push(Change::Unyanked(new)),
+                    _ => {}
+                },
+                None => res.push(Change::Added(new)),
+            }
+        }
+        for (name, version) in removed.into_keys() {
+            res.push(Change::Deleted { name, version });
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicUsize;
+
+    /// A directory under the system temp dir that is removed again when dropped.
+    struct TempRepoDir(PathBuf);
+
+    impl TempRepoDir {
+        fn new() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("time moves forward")
+                .as_nanos();
+            let path = std::env::temp_dir().join(format!(
+                "crates-index-diff-test-{}-{}",
+                nanos,
+                COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            fs::create_dir_all(&path).expect("can create temp dir");
+            TempRepoDir(path)
+        }
+    }
+
+    impl Drop for TempRepoDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn crate_version(name: &str, vers: &str, yanked: bool) -> CrateVersion {
+        CrateVersion {
+            name: name.into(),
+            vers: vers.into(),
+            yanked,
+        }
+    }
+
+    /// Render `crate_version` the way it would appear as a line in a crates.io index file.
+    fn line(name: &str, vers: &str, yanked: bool) -> String {
+        format!("{{\"name\":\"{}\",\"vers\":\"{}\",\"yanked\":{}}}\n", name, vers, yanked)
+    }
+
+    /// Commit `contents` as the sole content of the file `foo`, or remove it entirely if `None`.
+    fn commit(repo: &Repository, contents: Option<&str>, message: &str) -> Oid {
+        let mut index = repo.index().expect("repo has an index");
+        match contents {
+            Some(contents) => {
+                fs::write(repo.workdir().expect("non-bare repo").join("foo"), contents)
+                    .expect("can write file");
+                index.add_path(Path::new("foo")).expect("can stage file");
+            }
+            None => {
+                index.remove_path(Path::new("foo")).expect("can unstage file");
+            }
+        }
+        index.write().expect("can write index");
+        let tree = repo
+            .find_tree(index.write_tree().expect("can write tree"))
+            .expect("tree we just wrote exists");
+        let signature = git2::Signature::now("test", "test@example.com").expect("valid signature");
+        let parent_commit = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent_commit.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .expect("can commit")
+    }
+
+    fn empty_tree_object(repo: &Repository) -> Object<'_> {
+        let oid = repo
+            .treebuilder(None)
+            .expect("can create treebuilder")
+            .write()
+            .expect("can write empty tree");
+        repo.find_object(oid, None).expect("empty tree exists")
+    }
+
+    fn test_index(repo: Repository) -> Index {
+        Index {
+            repo,
+            seen_ref_name: LAST_SEEN_REFNAME,
+            remote_name: None,
+            branch_name: None,
+        }
+    }
+
+    /// A repo with one crate published, then yanked, then unyanked, then its entry removed.
+    fn repo_with_publish_yank_unyank_delete() -> (TempRepoDir, Repository, Vec<Oid>) {
+        let dir = TempRepoDir::new();
+        let repo = Repository::init(&dir.0).expect("can init repo");
+        let oids = vec![
+            commit(&repo, Some(&line("foo", "1.0.0", false)), "publish foo 1.0.0"),
+            commit(&repo, Some(&line("foo", "1.0.0", true)), "yank foo 1.0.0"),
+            commit(&repo, Some(&line("foo", "1.0.0", false)), "unyank foo 1.0.0"),
+            commit(&repo, None, "delete foo"),
+        ];
+        (dir, repo, oids)
+    }
+
+    #[test]
+    fn change_added_on_publish() {
+        let (_dir, repo, oids) = repo_with_publish_yank_unyank_delete();
+        let index = test_index(repo);
+        let empty = empty_tree_object(index.repository());
+        let to = index.repository().find_object(oids[0], None).unwrap();
+        let changes = index
+            .changes_from_objects(&empty, &to, &mut Discard, &AtomicBool::new(false))
+            .unwrap();
+        assert_eq!(changes, vec![Change::Added(crate_version("foo", "1.0.0", false))]);
+    }
+
+    #[test]
+    fn change_yanked_between_publish_and_yank() {
+        let (_dir, repo, oids) = repo_with_publish_yank_unyank_delete();
+        let index = test_index(repo);
+        let from = index.repository().find_object(oids[0], None).unwrap();
+        let to = index.repository().find_object(oids[1], None).unwrap();
+        let changes = index
+            .changes_from_objects(&from, &to, &mut Discard, &AtomicBool::new(false))
+            .unwrap();
+        assert_eq!(changes, vec![Change::Yanked(crate_version("foo", "1.0.0", true))]);
+    }
+
+    #[test]
+    fn change_unyanked_between_yank_and_unyank() {
+        let (_dir, repo, oids) = repo_with_publish_yank_unyank_delete();
+        let index = test_index(repo);
+        let from = index.repository().find_object(oids[1], None).unwrap();
+        let to = index.repository().find_object(oids[2], None).unwrap();
+        let changes = index
+            .changes_from_objects(&from, &to, &mut Discard, &AtomicBool::new(false))
+            .unwrap();
+        assert_eq!(changes, vec![Change::Unyanked(crate_version("foo", "1.0.0", false))]);
+    }
+
+    #[test]
+    fn change_deleted_when_file_removed() {
+        let (_dir, repo, oids) = repo_with_publish_yank_unyank_delete();
+        let index = test_index(repo);
+        let from = index.repository().find_object(oids[2], None).unwrap();
+        let to = index.repository().find_object(oids[3], None).unwrap();
+        let changes = index
+            .changes_from_objects(&from, &to, &mut Discard, &AtomicBool::new(false))
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![Change::Deleted {
+                name: "foo".into(),
+                version: "1.0.0".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn ordered_root_commit_diffs_against_empty_tree() {
+        let (_dir, repo, oids) = repo_with_publish_yank_unyank_delete();
+        let index = test_index(repo);
+        // Doesn't peel to a commit, so the revwalk isn't told to `hide` anything and walks all
+        // the way back to the root commit, which has no parent to diff against.
+        let non_commit = empty_tree_object(index.repository());
+        let to = index.repository().find_object(oids[0], None).unwrap();
+        let changes = index
+            .changes_from_objects_ordered(&non_commit, &to, &mut Discard, &AtomicBool::new(false))
+            .unwrap();
+        assert_eq!(changes, vec![Change::Added(crate_version("foo", "1.0.0", false))]);
+    }
+
+    #[test]
+    fn ordered_reports_publish_and_yank_as_two_separate_changes() {
+        let (_dir, repo, oids) = repo_with_publish_yank_unyank_delete();
+        let index = test_index(repo);
+        let non_commit = empty_tree_object(index.repository());
+        let to = index.repository().find_object(oids[1], None).unwrap();
+        let changes = index
+            .changes_from_objects_ordered(&non_commit, &to, &mut Discard, &AtomicBool::new(false))
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                Change::Added(crate_version("foo", "1.0.0", false)),
+                Change::Yanked(crate_version("foo", "1.0.0", true)),
+            ]
+        );
     }
 }
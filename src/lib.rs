@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+mod index;
+
+pub use index::{was_interrupted, Change, CloneOptions, Discard, Index, Progress};
+
+/// A single version of a crate as it appears in the crates.io index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrateVersion {
+    pub name: String,
+    pub vers: String,
+    #[serde(default)]
+    pub yanked: bool,
+}